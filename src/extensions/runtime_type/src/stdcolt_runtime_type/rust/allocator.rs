@@ -2,6 +2,8 @@
 #![allow(non_snake_case)]
 #![allow(non_upper_case_globals)]
 
+extern crate alloc as rust_alloc;
+
 use core::ffi::c_void;
 use core::os::raw::{c_int, c_longlong};
 
@@ -59,3 +61,443 @@ pub struct stdcolt_ext_rt_Allocator {
 extern "C" {
     pub fn stdcolt_ext_rt_default_allocator() -> stdcolt_ext_rt_RecipeAllocator;
 }
+
+/// Adapts an instantiated [`stdcolt_ext_rt_Allocator`] to Rust's global allocator
+/// interface, so it can be installed with `#[global_allocator]` and back every
+/// `alloc`-backed `Box`/`Vec`/`String` in the program.
+pub struct StdcoltGlobalAlloc {
+    allocator: stdcolt_ext_rt_Allocator,
+}
+
+impl StdcoltGlobalAlloc {
+    /// Wraps an already-constructed `stdcolt_ext_rt_Allocator`.
+    ///
+    /// # Safety
+    /// `allocator` must have been produced by `allocator_construct` (or an
+    /// equivalent) and must not be used or destructed anywhere else for as
+    /// long as this wrapper is alive.
+    pub const unsafe fn new(allocator: stdcolt_ext_rt_Allocator) -> Self {
+        Self { allocator }
+    }
+}
+
+// SAFETY: callers are required to hand us an allocator whose C-side
+// implementation is safe to call concurrently from multiple threads, as is
+// expected of any `GlobalAlloc` implementor.
+unsafe impl Sync for StdcoltGlobalAlloc {}
+
+unsafe impl core::alloc::GlobalAlloc for StdcoltGlobalAlloc {
+    unsafe fn alloc(&self, layout: core::alloc::Layout) -> *mut u8 {
+        let alloc_fn = match self.allocator.allocator_alloc {
+            Some(f) => f,
+            None => return core::ptr::null_mut(),
+        };
+        let block = alloc_fn(
+            self.allocator.state,
+            layout.size() as uint64_t,
+            layout.align() as uint64_t,
+        );
+        block.ptr as *mut u8
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: core::alloc::Layout) -> *mut u8 {
+        let ptr = self.alloc(layout);
+        if !ptr.is_null() {
+            core::ptr::write_bytes(ptr, 0, layout.size());
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: core::alloc::Layout) {
+        let Some(dealloc_fn) = self.allocator.allocator_dealloc else {
+            return;
+        };
+        let block = stdcolt_ext_rt_Block {
+            ptr: ptr as *mut c_void,
+            size: layout.size() as uint64_t,
+        };
+        dealloc_fn(self.allocator.state, &block);
+    }
+}
+
+impl Drop for StdcoltGlobalAlloc {
+    fn drop(&mut self) {
+        if let Some(destruct_fn) = self.allocator.allocator_destruct {
+            // SAFETY: `self.allocator` is uniquely owned by this wrapper per
+            // the contract of `new`, so it is safe to destruct exactly once.
+            unsafe { destruct_fn(self.allocator.state) };
+        }
+    }
+}
+
+/// `allocator_construct` returned nonzero, or `state` couldn't be allocated.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct AllocInitError;
+
+/// `allocator_alloc` returned a null block.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct AllocError;
+
+/// Safe wrapper around a `stdcolt_ext_rt_RecipeAllocator`.
+pub struct RecipeAllocator(stdcolt_ext_rt_RecipeAllocator);
+
+impl RecipeAllocator {
+    /// Wraps a recipe, e.g. from `stdcolt_ext_rt_default_allocator`.
+    pub const fn new(recipe: stdcolt_ext_rt_RecipeAllocator) -> Self {
+        Self(recipe)
+    }
+
+    /// Allocates `state` for the recipe and constructs it into an
+    /// [`Allocator`], never panicking or aborting on failure.
+    pub fn instantiate(self) -> Result<Allocator, AllocInitError> {
+        let state_layout = core::alloc::Layout::from_size_align(
+            self.0.allocator_sizeof as usize,
+            (self.0.allocator_alignof as usize).max(1),
+        )
+        .map_err(|_| AllocInitError)?;
+
+        let state = if state_layout.size() == 0 {
+            core::ptr::NonNull::dangling().as_ptr()
+        } else {
+            // SAFETY: `state_layout` has a nonzero size checked above.
+            let raw = unsafe { rust_alloc::alloc::alloc(state_layout) };
+            if raw.is_null() {
+                return Err(AllocInitError);
+            }
+            raw
+        };
+
+        if let Some(construct_fn) = self.0.allocator_construct {
+            // SAFETY: `state` points to a fresh allocation of
+            // `allocator_sizeof`/`allocator_alignof` bytes, matching what
+            // `allocator_construct` expects to initialize in place.
+            let status = unsafe { construct_fn(state as *mut c_void) };
+            if status != 0 {
+                if state_layout.size() != 0 {
+                    // SAFETY: `state` was allocated with `state_layout` above
+                    // and construction failed, so nothing else can reference it.
+                    unsafe { rust_alloc::alloc::dealloc(state, state_layout) };
+                }
+                return Err(AllocInitError);
+            }
+        }
+
+        Ok(Allocator {
+            raw: stdcolt_ext_rt_Allocator {
+                state: state as *mut c_void,
+                allocator_alloc: self.0.allocator_alloc,
+                allocator_dealloc: self.0.allocator_dealloc,
+                allocator_destruct: self.0.allocator_destruct,
+            },
+            state_layout,
+        })
+    }
+}
+
+/// An instantiated, owned `stdcolt_ext_rt_Allocator`. Frees its own `state`
+/// storage and runs `allocator_destruct` on drop.
+pub struct Allocator {
+    raw: stdcolt_ext_rt_Allocator,
+    state_layout: core::alloc::Layout,
+}
+
+impl Allocator {
+    /// Requests `size` bytes aligned to `align` from the underlying
+    /// allocator, never panicking or aborting on failure.
+    ///
+    /// Returns the raw, non-owning `stdcolt_ext_rt_Block`; callers are
+    /// responsible for eventually passing it back to `allocator_dealloc`
+    /// themselves. Prefer [`Allocator::alloc_owned`] unless that manual
+    /// bookkeeping is actually wanted.
+    pub fn try_alloc(&self, size: u64, align: u64) -> Result<stdcolt_ext_rt_Block, AllocError> {
+        let alloc_fn = self.raw.allocator_alloc.ok_or(AllocError)?;
+        // SAFETY: `self.raw.state` is valid for the lifetime of `self`.
+        let block = unsafe { alloc_fn(self.raw.state, size, align) };
+        if block.ptr.is_null() {
+            Err(AllocError)
+        } else {
+            Ok(block)
+        }
+    }
+
+    /// Like [`Allocator::try_alloc`], but ties the returned block's
+    /// lifetime to `self` and frees it automatically on drop, so the
+    /// compiler rejects using the block after this allocator is gone and
+    /// there is no `allocator_dealloc` call to forget.
+    pub fn alloc_owned(&self, size: u64, align: u64) -> Result<OwnedBlock<'_>, AllocError> {
+        let block = self.try_alloc(size, align)?;
+        Ok(OwnedBlock {
+            block,
+            allocator: self,
+        })
+    }
+
+    /// Allocates `data.len()` bytes aligned to `align`, copies `data` into
+    /// them, and returns the result as a [`ReadOnlyBlock`] that derefs to
+    /// `&[u8]` but never `&mut [u8]`. Unlike [`Allocator::alloc_owned`], the
+    /// memory is always initialized before it is exposed, since
+    /// `ReadOnlyBlock` never offers a way to write to it afterwards.
+    pub fn try_alloc_readonly(&self, data: &[u8], align: u64) -> Result<ReadOnlyBlock<'_>, AllocError> {
+        let block = self.try_alloc(data.len() as u64, align)?;
+        // SAFETY: `block.ptr` is non-null and valid for `block.size >=
+        // data.len()` bytes, freshly returned by `allocator_alloc` and not
+        // yet aliased by any other reference.
+        unsafe {
+            core::ptr::copy_nonoverlapping(data.as_ptr(), block.ptr as *mut u8, data.len());
+        }
+        Ok(ReadOnlyBlock {
+            block,
+            len: data.len(),
+            allocator: self,
+        })
+    }
+}
+
+/// An owned `stdcolt_ext_rt_Block` borrowed from the [`Allocator`] that
+/// produced it. The borrow means a block can never outlive its allocator,
+/// and `Drop` always returns the block to that same allocator via
+/// `allocator_dealloc` — eliminating both use-after-free and freeing a
+/// block against the wrong allocator.
+pub struct OwnedBlock<'a> {
+    block: stdcolt_ext_rt_Block,
+    allocator: &'a Allocator,
+}
+
+impl<'a> OwnedBlock<'a> {
+    /// The block's starting address.
+    pub fn as_ptr(&self) -> *mut u8 {
+        self.block.ptr as *mut u8
+    }
+
+    /// The block's size in bytes, as reported by the allocator (which may
+    /// exceed the originally requested size).
+    pub fn size(&self) -> u64 {
+        self.block.size
+    }
+
+    /// Freezes this block into a [`ReadOnlyBlock`] exposing its leading
+    /// `len` bytes: the type system no longer offers mutable access to
+    /// them.
+    ///
+    /// # Safety
+    /// `len` must not exceed `self.size()`, and the first `len` bytes of
+    /// the block must already be initialized, since `ReadOnlyBlock` safely
+    /// derefs to `&[u8]` over them.
+    pub unsafe fn freeze(self, len: usize) -> ReadOnlyBlock<'a> {
+        let this = core::mem::ManuallyDrop::new(self);
+        ReadOnlyBlock {
+            block: this.block,
+            len,
+            allocator: this.allocator,
+        }
+    }
+}
+
+impl<'a> Drop for OwnedBlock<'a> {
+    fn drop(&mut self) {
+        if let Some(dealloc_fn) = self.allocator.raw.allocator_dealloc {
+            // SAFETY: `self.block` was produced by `self.allocator` and has
+            // not been freed before, since `OwnedBlock` only reaches `Drop`
+            // once.
+            unsafe { dealloc_fn(self.allocator.raw.state, &self.block) };
+        }
+    }
+}
+
+/// A read-only [`OwnedBlock`]: produced by [`Allocator::try_alloc_readonly`]
+/// or by [`OwnedBlock::freeze`]ing an initialized block. Derefs to `&[u8]`
+/// but not `&mut [u8]`, so the compiler rejects writes to frozen memory.
+/// `readonly`-ness is tracked purely on the Rust side; deallocation still
+/// goes through the same `allocator_dealloc` as any other block.
+pub struct ReadOnlyBlock<'a> {
+    block: stdcolt_ext_rt_Block,
+    /// How many leading bytes of `block` are known to be initialized, and
+    /// thus safe to expose through `Deref`. May be less than `block.size`
+    /// when the allocator over-allocated beyond what was written.
+    len: usize,
+    allocator: &'a Allocator,
+}
+
+impl<'a> ReadOnlyBlock<'a> {
+    /// The block's size in bytes, as reported by the allocator (which may
+    /// exceed `self.len()`, the initialized prefix exposed by `Deref`).
+    pub fn size(&self) -> u64 {
+        self.block.size
+    }
+}
+
+impl<'a> core::ops::Deref for ReadOnlyBlock<'a> {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        // SAFETY: the first `self.len` bytes of `self.block` are
+        // initialized per the safety contract of whichever constructor
+        // produced this `ReadOnlyBlock`, and `ReadOnlyBlock` only ever
+        // hands out shared references to them.
+        unsafe { core::slice::from_raw_parts(self.block.ptr as *const u8, self.len) }
+    }
+}
+
+impl<'a> Drop for ReadOnlyBlock<'a> {
+    fn drop(&mut self) {
+        if let Some(dealloc_fn) = self.allocator.raw.allocator_dealloc {
+            // SAFETY: `self.block` was produced by `self.allocator` and has
+            // not been freed before, since `ReadOnlyBlock` only reaches
+            // `Drop` once.
+            unsafe { dealloc_fn(self.allocator.raw.state, &self.block) };
+        }
+    }
+}
+
+impl Drop for Allocator {
+    fn drop(&mut self) {
+        if let Some(destruct_fn) = self.raw.allocator_destruct {
+            // SAFETY: `self.raw.state` is uniquely owned by this `Allocator`.
+            unsafe { destruct_fn(self.raw.state) };
+        }
+        if self.state_layout.size() != 0 {
+            // SAFETY: `self.raw.state` was allocated with `self.state_layout`
+            // in `RecipeAllocator::instantiate` and is not used after this.
+            unsafe {
+                rust_alloc::alloc::dealloc(self.raw.state as *mut u8, self.state_layout)
+            };
+        }
+    }
+}
+
+#[cfg(feature = "allocator_api")]
+mod allocator_api {
+    use super::{stdcolt_ext_rt_Allocator, stdcolt_ext_rt_Block};
+    use core::alloc::{AllocError, Layout};
+    use core::ffi::c_void;
+    use core::ptr::NonNull;
+    use super::rust_alloc::rc::Rc;
+
+    struct Inner {
+        allocator: stdcolt_ext_rt_Allocator,
+    }
+
+    impl Drop for Inner {
+        fn drop(&mut self) {
+            if let Some(destruct_fn) = self.allocator.allocator_destruct {
+                // SAFETY: `Inner` is only reachable through the `Rc` below, so
+                // this runs exactly once, after every `StdcoltAllocator`
+                // sharing it has been dropped.
+                unsafe { destruct_fn(self.allocator.state) };
+            }
+        }
+    }
+
+    /// Adapts an instantiated [`stdcolt_ext_rt_Allocator`] to the unstable
+    /// `core::alloc::Allocator` trait, so containers can be built with a
+    /// specific stdcolt arena/pool via e.g. `Vec::new_in(allocator)` instead
+    /// of relying on the global allocator.
+    ///
+    /// `stdcolt_ext_rt_Allocator` itself is `Copy` but merely a handle onto
+    /// C-owned `state`; this wrapper is `Clone` by reference-counting that
+    /// shared state so that every clone can call into the same underlying
+    /// allocator and `allocator_destruct` only runs once, when the last
+    /// clone is dropped.
+    #[derive(Clone)]
+    pub struct StdcoltAllocator {
+        inner: Rc<Inner>,
+    }
+
+    impl StdcoltAllocator {
+        /// Wraps an already-constructed `stdcolt_ext_rt_Allocator`.
+        ///
+        /// # Safety
+        /// `allocator` must have been produced by `allocator_construct` (or
+        /// an equivalent) and must not be destructed anywhere else for as
+        /// long as this wrapper (or any of its clones) is alive.
+        pub unsafe fn new(allocator: stdcolt_ext_rt_Allocator) -> Self {
+            Self {
+                inner: Rc::new(Inner { allocator }),
+            }
+        }
+    }
+
+    unsafe impl core::alloc::Allocator for StdcoltAllocator {
+        fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+            if layout.size() == 0 {
+                // The `Allocator` contract requires zero-sized requests to
+                // succeed with a dangling, aligned pointer rather than
+                // reaching the C allocator at all.
+                let ptr = NonNull::new(layout.align() as *mut u8).ok_or(AllocError)?;
+                return Ok(NonNull::slice_from_raw_parts(ptr, 0));
+            }
+            let alloc_fn = self.inner.allocator.allocator_alloc.ok_or(AllocError)?;
+            // SAFETY: `state` is a valid allocator handle for the lifetime of
+            // `self.inner`, and `alloc_fn` is a well-formed C function
+            // pointer per the `stdcolt_ext_rt_Allocator` contract.
+            let block = unsafe {
+                alloc_fn(
+                    self.inner.allocator.state,
+                    layout.size() as super::uint64_t,
+                    layout.align() as super::uint64_t,
+                )
+            };
+            block_to_slice(block)
+        }
+
+        unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+            if layout.size() == 0 {
+                // `ptr` is the dangling pointer `allocate` made up above,
+                // never a real C allocation — nothing to free.
+                return;
+            }
+            let Some(dealloc_fn) = self.inner.allocator.allocator_dealloc else {
+                return;
+            };
+            let block = stdcolt_ext_rt_Block {
+                ptr: ptr.as_ptr() as *mut c_void,
+                size: layout.size() as super::uint64_t,
+            };
+            dealloc_fn(self.inner.allocator.state, &block);
+        }
+
+        unsafe fn grow(
+            &self,
+            ptr: NonNull<u8>,
+            old_layout: Layout,
+            new_layout: Layout,
+        ) -> Result<NonNull<[u8]>, AllocError> {
+            let new_block = self.allocate(new_layout)?;
+            core::ptr::copy_nonoverlapping(
+                ptr.as_ptr(),
+                new_block.as_ptr() as *mut u8,
+                old_layout.size(),
+            );
+            self.deallocate(ptr, old_layout);
+            Ok(new_block)
+        }
+
+        unsafe fn shrink(
+            &self,
+            ptr: NonNull<u8>,
+            old_layout: Layout,
+            new_layout: Layout,
+        ) -> Result<NonNull<[u8]>, AllocError> {
+            let new_block = self.allocate(new_layout)?;
+            core::ptr::copy_nonoverlapping(
+                ptr.as_ptr(),
+                new_block.as_ptr() as *mut u8,
+                new_layout.size(),
+            );
+            self.deallocate(ptr, old_layout);
+            Ok(new_block)
+        }
+    }
+
+    /// Converts a possibly-null [`stdcolt_ext_rt_Block`] into the
+    /// over-allocation-aware slice pointer `Allocator::allocate` expects,
+    /// reporting the real `size` the allocator returned (which may exceed
+    /// the request) so callers can exploit any excess capacity.
+    fn block_to_slice(block: stdcolt_ext_rt_Block) -> Result<NonNull<[u8]>, AllocError> {
+        let ptr = NonNull::new(block.ptr as *mut u8).ok_or(AllocError)?;
+        Ok(NonNull::slice_from_raw_parts(ptr, block.size as usize))
+    }
+}
+
+#[cfg(feature = "allocator_api")]
+pub use allocator_api::StdcoltAllocator;
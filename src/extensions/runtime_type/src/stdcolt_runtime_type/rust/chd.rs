@@ -0,0 +1,255 @@
+#![allow(non_camel_case_types)]
+#![allow(non_snake_case)]
+
+//! A pure-Rust CHD (Compress-Hash-and-Displace) minimal perfect hash,
+//! exposed as a `stdcolt_ext_rt_RecipePerfectHashFunction` so a stdcolt
+//! build with no C runtime still has a working default PHF.
+
+extern crate alloc as rust_alloc;
+
+use core::ffi::c_void;
+use rust_alloc::vec::Vec;
+
+use super::perfect_hash_function::{
+    int32_t, stdcolt_ext_rt_Key, stdcolt_ext_rt_RecipePerfectHashFunction, uint32_t, uint64_t,
+};
+
+/// Upper bound on how many displacement values are tried for a single
+/// bucket before giving up and reseeding the whole construction.
+const MAX_DISPLACEMENT_PROBES: u64 = 4096;
+
+/// Upper bound on how many times construction reseeds and restarts before
+/// reporting failure.
+const MAX_RESEED_ATTEMPTS: u64 = 64;
+
+/// `splitmix64`, used to derive fresh per-attempt seeds and, in
+/// `displaced_slot`, to mix `seed1`, `seed2` and `d` into the seed of the
+/// slot hash; the key bytes themselves are hashed by `seeded_hash`.
+fn splitmix64(mut x: u64) -> u64 {
+    x = x.wrapping_add(0x9E3779B97F4A7C15);
+    let mut z = x;
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+    z ^ (z >> 31)
+}
+
+/// FNV-1a over `data`, seeded so the bucket hash (`seed0`) and the
+/// displaced slot hash (a seed mixed from `seed1`, `seed2` and `d`) differ.
+fn seeded_hash(data: &[u8], seed: u64) -> u64 {
+    let mut h = seed ^ 0xcbf29ce484222325;
+    for &byte in data {
+        h ^= byte as u64;
+        h = h.wrapping_mul(0x100000001b3);
+    }
+    h
+}
+
+/// Owned state behind a constructed `stdcolt_ext_rt_PerfectHashFunction`:
+/// the bucket count `r`, the key count `n`, the three seeds that produced
+/// the mapping, and the per-bucket displacement table `D[r]`.
+struct ChdState {
+    r: u64,
+    n: u64,
+    seed0: u64,
+    seed1: u64,
+    seed2: u64,
+    displacement: Vec<u32>,
+}
+
+impl ChdState {
+    fn lookup(&self, key: &[u8]) -> uint64_t {
+        if self.n == 0 {
+            return 0;
+        }
+        let bucket = (seeded_hash(key, self.seed0) % self.r) as usize;
+        let d = self.displacement[bucket] as u64;
+        displaced_slot(key, self.seed1, self.seed2, d, self.n)
+    }
+}
+
+/// Computes `h(key, d)`, the candidate slot for `key` under displacement
+/// `d`: a fresh `splitmix64`-scrambled seed per `d` rather than a plain
+/// `seed1 + d * seed2` combination, so the candidate slots for a growing
+/// `d` don't fall into the short cycle that an affine function mod `n`
+/// hits whenever `seed2` shares a factor with `n`.
+fn displaced_slot(key: &[u8], seed1: u64, seed2: u64, d: u64, n: u64) -> u64 {
+    let mixed = splitmix64(seed1 ^ splitmix64(seed2 ^ d));
+    seeded_hash(key, mixed) % n
+}
+
+/// Builds a `ChdState` mapping `keys` onto `[0, keys.len())` with no
+/// collisions, reseeding and restarting whenever a bucket's displacement
+/// search runs past `MAX_DISPLACEMENT_PROBES`. Returns `Err(())` if no
+/// seed works within `MAX_RESEED_ATTEMPTS`.
+fn build_chd(keys: &[&[u8]]) -> Result<ChdState, ()> {
+    let n = keys.len() as u64;
+    if n == 0 {
+        return Ok(ChdState {
+            r: 0,
+            n: 0,
+            seed0: 0,
+            seed1: 0,
+            seed2: 0,
+            displacement: Vec::new(),
+        });
+    }
+    let r = (n / 5).max(1);
+
+    let mut root_seed = 0x2545F4914F6CDD1Du64;
+    for _attempt in 0..MAX_RESEED_ATTEMPTS {
+        root_seed = splitmix64(root_seed);
+        let seed0 = splitmix64(root_seed);
+        let seed1 = splitmix64(seed0);
+        let seed2 = splitmix64(seed1);
+
+        let mut buckets: Vec<Vec<usize>> = (0..r).map(|_| Vec::new()).collect();
+        for (i, key) in keys.iter().enumerate() {
+            let bucket = (seeded_hash(key, seed0) % r) as usize;
+            buckets[bucket].push(i);
+        }
+
+        let mut bucket_order: Vec<usize> = (0..r as usize).collect();
+        bucket_order.sort_by_key(|&b| core::cmp::Reverse(buckets[b].len()));
+
+        let mut occupied = rust_alloc::vec![false; n as usize];
+        let mut displacement = rust_alloc::vec![0u32; r as usize];
+        let mut ok = true;
+
+        'buckets: for &bucket in &bucket_order {
+            if buckets[bucket].is_empty() {
+                continue;
+            }
+            for d in 0..MAX_DISPLACEMENT_PROBES {
+                let slots: Vec<usize> = buckets[bucket]
+                    .iter()
+                    .map(|&i| displaced_slot(keys[i], seed1, seed2, d, n) as usize)
+                    .collect();
+
+                let all_free = slots.iter().all(|&s| !occupied[s]);
+                let all_distinct = {
+                    let mut sorted = slots.clone();
+                    sorted.sort_unstable();
+                    sorted.windows(2).all(|w| w[0] != w[1])
+                };
+
+                if all_free && all_distinct {
+                    for &s in &slots {
+                        occupied[s] = true;
+                    }
+                    displacement[bucket] = d as u32;
+                    continue 'buckets;
+                }
+            }
+            ok = false;
+            break;
+        }
+
+        if ok {
+            return Ok(ChdState {
+                r,
+                n,
+                seed0,
+                seed1,
+                seed2,
+                displacement,
+            });
+        }
+    }
+    Err(())
+}
+
+unsafe extern "C" fn chd_construct(
+    state: *mut c_void,
+    keys: *const stdcolt_ext_rt_Key,
+    keys_len: uint64_t,
+) -> int32_t {
+    let keys_slice = if keys_len == 0 {
+        &[][..]
+    } else {
+        core::slice::from_raw_parts(keys, keys_len as usize)
+    };
+    let byte_keys: Vec<&[u8]> = keys_slice
+        .iter()
+        .map(|k| core::slice::from_raw_parts(k.key as *const u8, k.size as usize))
+        .collect();
+
+    match build_chd(&byte_keys) {
+        Ok(chd) => {
+            (state as *mut ChdState).write(chd);
+            0
+        }
+        Err(()) => -1,
+    }
+}
+
+unsafe extern "C" fn chd_destruct(state: *mut c_void) {
+    core::ptr::drop_in_place(state as *mut ChdState);
+}
+
+unsafe extern "C" fn chd_lookup(state: *mut c_void, key: *const stdcolt_ext_rt_Key) -> uint64_t {
+    let chd = &*(state as *const ChdState);
+    let key = &*key;
+    let bytes = core::slice::from_raw_parts(key.key as *const u8, key.size as usize);
+    chd.lookup(bytes)
+}
+
+/// The pure-Rust CHD recipe: a drop-in replacement for
+/// `stdcolt_ext_rt_default_perfect_hash_function` that needs no C backend.
+pub fn stdcolt_ext_rt_rust_chd_perfect_hash_function() -> stdcolt_ext_rt_RecipePerfectHashFunction {
+    stdcolt_ext_rt_RecipePerfectHashFunction {
+        phf_sizeof: core::mem::size_of::<ChdState>() as uint32_t,
+        phf_alignof: core::mem::align_of::<ChdState>() as uint32_t,
+        phf_construct: Some(chd_construct),
+        phf_destruct: Some(chd_destruct),
+        phf_lookup: Some(chd_lookup),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_minimal_perfect(keys: &[&[u8]]) {
+        let chd = build_chd(keys).expect("build_chd should find a working seed");
+        let mut indices: Vec<u64> = keys.iter().map(|k| chd.lookup(k)).collect();
+        indices.sort_unstable();
+        let expected: Vec<u64> = (0..keys.len() as u64).collect();
+        assert_eq!(indices, expected, "lookup must be a bijection onto [0, n)");
+    }
+
+    #[test]
+    fn empty_key_set() {
+        let chd = build_chd(&[]).unwrap();
+        assert_eq!(chd.n, 0);
+        assert_eq!(chd.r, 0);
+    }
+
+    #[test]
+    fn fewer_than_five_keys() {
+        let keys: Vec<&[u8]> = rust_alloc::vec![&b"a"[..], &b"bb"[..], &b"ccc"[..]];
+        assert_minimal_perfect(&keys);
+    }
+
+    #[test]
+    fn moderate_key_set() {
+        let owned: Vec<rust_alloc::string::String> =
+            (0..50).map(|i| rust_alloc::format!("key-{i}")).collect();
+        let keys: Vec<&[u8]> = owned.iter().map(|s| s.as_bytes()).collect();
+        assert_minimal_perfect(&keys);
+    }
+
+    #[test]
+    fn key_set_large_enough_to_need_several_buckets() {
+        let owned: Vec<rust_alloc::string::String> =
+            (0..500).map(|i| rust_alloc::format!("key-{i}")).collect();
+        let keys: Vec<&[u8]> = owned.iter().map(|s| s.as_bytes()).collect();
+        assert_minimal_perfect(&keys);
+    }
+
+    #[test]
+    fn single_key() {
+        let keys: Vec<&[u8]> = rust_alloc::vec![&b"only"[..]];
+        assert_minimal_perfect(&keys);
+    }
+}
+
@@ -2,6 +2,8 @@
 #![allow(non_snake_case)]
 #![allow(non_upper_case_globals)]
 
+extern crate alloc as rust_alloc;
+
 use core::ffi::c_void;
 
 pub type uint64_t = u64;
@@ -51,3 +53,119 @@ pub struct stdcolt_ext_rt_PerfectHashFunction {
 extern "C" {
     pub fn stdcolt_ext_rt_default_perfect_hash_function() -> stdcolt_ext_rt_RecipePerfectHashFunction;
 }
+
+/// `phf_construct` returned nonzero, or `state` couldn't be allocated.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct PhfBuildError;
+
+/// Safe wrapper around a `stdcolt_ext_rt_RecipePerfectHashFunction`.
+pub struct RecipePerfectHashFunction(stdcolt_ext_rt_RecipePerfectHashFunction);
+
+impl RecipePerfectHashFunction {
+    /// Wraps a recipe, e.g. from `stdcolt_ext_rt_default_perfect_hash_function`.
+    pub const fn new(recipe: stdcolt_ext_rt_RecipePerfectHashFunction) -> Self {
+        Self(recipe)
+    }
+
+    /// Allocates `state` for the recipe and constructs a perfect hash
+    /// function over `keys`, never panicking or aborting on failure.
+    pub fn build(self, keys: &[&[u8]]) -> Result<PerfectHashFunction, PhfBuildError> {
+        let state_layout = core::alloc::Layout::from_size_align(
+            self.0.phf_sizeof as usize,
+            (self.0.phf_alignof as usize).max(1),
+        )
+        .map_err(|_| PhfBuildError)?;
+
+        let state = if state_layout.size() == 0 {
+            core::ptr::NonNull::dangling().as_ptr()
+        } else {
+            // SAFETY: `state_layout` has a nonzero size checked above.
+            let raw = unsafe { rust_alloc::alloc::alloc(state_layout) };
+            if raw.is_null() {
+                return Err(PhfBuildError);
+            }
+            raw
+        };
+
+        let raw_keys: rust_alloc::vec::Vec<stdcolt_ext_rt_Key> = keys
+            .iter()
+            .map(|key| stdcolt_ext_rt_Key {
+                key: key.as_ptr() as *const c_void,
+                size: key.len() as uint64_t,
+            })
+            .collect();
+
+        if let Some(construct_fn) = self.0.phf_construct {
+            // SAFETY: `state` points to a fresh allocation of
+            // `phf_sizeof`/`phf_alignof` bytes, and `raw_keys` is a valid
+            // array of `raw_keys.len()` `stdcolt_ext_rt_Key`s borrowing
+            // `keys`, both of which outlive this call.
+            let status = unsafe {
+                construct_fn(
+                    state as *mut c_void,
+                    raw_keys.as_ptr(),
+                    raw_keys.len() as uint64_t,
+                )
+            };
+            if status != 0 {
+                if state_layout.size() != 0 {
+                    // SAFETY: `state` was allocated with `state_layout` above
+                    // and construction failed, so nothing else can reference it.
+                    unsafe { rust_alloc::alloc::dealloc(state, state_layout) };
+                }
+                return Err(PhfBuildError);
+            }
+        }
+
+        Ok(PerfectHashFunction {
+            raw: stdcolt_ext_rt_PerfectHashFunction {
+                state: state as *mut c_void,
+                phf_lookup: self.0.phf_lookup,
+                phf_destruct: self.0.phf_destruct,
+            },
+            state_layout,
+        })
+    }
+}
+
+/// An instantiated, owned `stdcolt_ext_rt_PerfectHashFunction`. Frees its
+/// own `state` storage and runs `phf_destruct` on drop.
+pub struct PerfectHashFunction {
+    raw: stdcolt_ext_rt_PerfectHashFunction,
+    state_layout: core::alloc::Layout,
+}
+
+impl PerfectHashFunction {
+    /// Looks up the dense index assigned to `key`. Behavior is unspecified
+    /// if `key` was not part of the set this function was built from.
+    pub fn lookup(&self, key: &[u8]) -> uint64_t {
+        let raw_key = stdcolt_ext_rt_Key {
+            key: key.as_ptr() as *const c_void,
+            size: key.len() as uint64_t,
+        };
+        match self.raw.phf_lookup {
+            // SAFETY: `self.raw.state` is valid for the lifetime of `self`,
+            // and `raw_key` is valid for the duration of this call.
+            Some(lookup_fn) => unsafe { lookup_fn(self.raw.state, &raw_key) },
+            None => 0,
+        }
+    }
+}
+
+impl Drop for PerfectHashFunction {
+    fn drop(&mut self) {
+        if let Some(destruct_fn) = self.raw.phf_destruct {
+            // SAFETY: `self.raw.state` is uniquely owned by this
+            // `PerfectHashFunction`.
+            unsafe { destruct_fn(self.raw.state) };
+        }
+        if self.state_layout.size() != 0 {
+            // SAFETY: `self.raw.state` was allocated with
+            // `self.state_layout` in `RecipePerfectHashFunction::build` and
+            // is not used after this.
+            unsafe {
+                rust_alloc::alloc::dealloc(self.raw.state as *mut u8, self.state_layout)
+            };
+        }
+    }
+}